@@ -2,10 +2,10 @@
 //!
 
 use crate::FdwRoutine;
-use pgx::prelude::{Date, Timestamp};
+use pgx::prelude::{Date, Timestamp, TimestampWithTimeZone};
 use pgx::{
     pg_sys::{self, Datum, Oid},
-    AllocatedByRust, FromDatum, IntoDatum, JsonB, PgBuiltInOids, PgOid,
+    AllocatedByRust, AnyNumeric, FromDatum, IntoDatum, JsonB, PgBuiltInOids, PgOid, Uuid,
 };
 use std::collections::HashMap;
 use std::fmt;
@@ -23,6 +23,9 @@ pub const FOREIGN_DATA_WRAPPER_RELATION_ID: pg_sys::Oid = 2328;
 /// Constant can be used in [validator](ForeignDataWrapper::validator)
 pub const FOREIGN_SERVER_RELATION_ID: pg_sys::Oid = 1417;
 
+/// Constant can be used in [validator](ForeignDataWrapper::validator)
+pub const FOREIGN_USER_MAPPING_RELATION_ID: pg_sys::Oid = 1418;
+
 /// Constant can be used in [validator](ForeignDataWrapper::validator)
 pub const FOREIGN_TABLE_RELATION_ID: pg_sys::Oid = 3118;
 
@@ -39,7 +42,11 @@ pub enum Cell {
     String(String),
     Date(Date),
     Timestamp(Timestamp),
+    TimestampTz(TimestampWithTimeZone),
     Json(JsonB),
+    Numeric(AnyNumeric),
+    Uuid(Uuid),
+    Bytea(Vec<u8>),
 }
 
 impl Clone for Cell {
@@ -55,7 +62,11 @@ impl Clone for Cell {
             Cell::String(v) => Cell::String(v.clone()),
             Cell::Date(v) => Cell::Date(v.clone()),
             Cell::Timestamp(v) => Cell::Timestamp(v.clone()),
+            Cell::TimestampTz(v) => Cell::TimestampTz(v.clone()),
             Cell::Json(v) => Cell::Json(JsonB(v.0.clone())),
+            Cell::Numeric(v) => Cell::Numeric(v.clone()),
+            Cell::Uuid(v) => Cell::Uuid(*v),
+            Cell::Bytea(v) => Cell::Bytea(v.clone()),
         }
     }
 }
@@ -71,9 +82,26 @@ impl fmt::Display for Cell {
             Cell::F64(v) => write!(f, "{}", v),
             Cell::I64(v) => write!(f, "{}", v),
             Cell::String(v) => write!(f, "'{}'", v),
-            Cell::Date(v) => write!(f, "{:?}", v),
-            Cell::Timestamp(v) => write!(f, "{:?}", v),
+            // use `{:?}` rather than Postgres' own text output for date/time
+            // types: the latter depends on the `DateStyle`/`TimeZone` GUCs, so
+            // it wouldn't round-trip to the same instant on a server with
+            // different settings, whereas `Debug` always renders a fixed,
+            // unambiguous ISO format in UTC.
+            Cell::Date(v) => write!(f, "'{:?}'", v),
+            Cell::Timestamp(v) => write!(f, "'{:?}'", v),
+            Cell::TimestampTz(v) => write!(f, "'{:?}'", v),
             Cell::Json(v) => write!(f, "{:?}", v),
+            Cell::Numeric(v) => write!(f, "{}", v),
+            Cell::Uuid(v) => write!(f, "'{}'", v),
+            // render as a fixed hex literal rather than relying on the
+            // `bytea_output` GUC (hex vs escape)
+            Cell::Bytea(v) => {
+                write!(f, "'\\x")?;
+                for b in v {
+                    write!(f, "{:02x}", b)?;
+                }
+                write!(f, "'")
+            }
         }
     }
 }
@@ -91,7 +119,11 @@ impl IntoDatum for Cell {
             Cell::String(v) => v.into_datum(),
             Cell::Date(v) => v.into_datum(),
             Cell::Timestamp(v) => v.into_datum(),
+            Cell::TimestampTz(v) => v.into_datum(),
             Cell::Json(v) => v.into_datum(),
+            Cell::Numeric(v) => v.into_datum(),
+            Cell::Uuid(v) => v.into_datum(),
+            Cell::Bytea(v) => v.into_datum(),
         }
     }
 
@@ -140,9 +172,21 @@ impl FromDatum for Cell {
             PgOid::BuiltIn(PgBuiltInOids::TIMESTAMPOID) => Some(Cell::Timestamp(
                 Timestamp::from_datum(datum, false).unwrap(),
             )),
+            PgOid::BuiltIn(PgBuiltInOids::TIMESTAMPTZOID) => Some(Cell::TimestampTz(
+                TimestampWithTimeZone::from_datum(datum, false).unwrap(),
+            )),
             PgOid::BuiltIn(PgBuiltInOids::JSONBOID) => {
                 Some(Cell::Json(JsonB::from_datum(datum, false).unwrap()))
             }
+            PgOid::BuiltIn(PgBuiltInOids::NUMERICOID) => {
+                Some(Cell::Numeric(AnyNumeric::from_datum(datum, false).unwrap()))
+            }
+            PgOid::BuiltIn(PgBuiltInOids::UUIDOID) => {
+                Some(Cell::Uuid(Uuid::from_datum(datum, false).unwrap()))
+            }
+            PgOid::BuiltIn(PgBuiltInOids::BYTEAOID) => {
+                Some(Cell::Bytea(Vec::<u8>::from_datum(datum, false).unwrap()))
+            }
             _ => None,
         }
     }
@@ -242,17 +286,211 @@ pub struct Qual {
 
 impl Qual {
     pub fn deparse(&self) -> String {
-        if self.use_or {
-            "".to_string()
-        } else {
-            match &self.value {
-                Value::Cell(cell) => format!("{} {} {}", self.field, self.operator, cell),
-                Value::Array(_) => unreachable!(),
+        match &self.value {
+            Value::Cell(cell) => format!("{} {} {}", self.field, self.operator, cell),
+            Value::Array(cells) => {
+                // rewrite 'field = (c1, c2, ...)' as 'field in (c1, c2, ...)'
+                let operator = if self.operator == "=" { "in" } else { &self.operator };
+                let values = cells
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                format!("{} {} ({})", self.field, operator, values)
             }
         }
     }
 }
 
+/// Deparse a full `WHERE` clause fragment from groups of [`Qual`]s
+///
+/// Each inner group is combined with `OR` and parenthesized when it has more
+/// than one member, e.g. a planner-supplied restriction clause's OR'd
+/// arguments; the groups themselves are combined with `AND`, e.g.
+/// `(a or b) and c` for `groups = [[a, b], [c]]`.
+///
+/// This is deliberately driven by the caller's grouping rather than by
+/// [`Qual::use_or`]: that flag only describes a single qual's own
+/// `Value::Array` (matched against any of its elements, as in the IN-list
+/// `id in (1, 2)`; [`Qual::deparse`] does not read it) and says
+/// nothing about how that qual relates to any other qual, so reusing it to
+/// decide cross-qual OR-grouping would wrongly glue together independent,
+/// AND'd conditions that each happen to be an IN-list (e.g.
+/// `id IN (1,2) AND status IN (3,4)`).
+///
+/// Useful for HTTP/SQL-backed wrappers that want to forward pushed-down
+/// restrictions to a remote source instead of scanning everything.
+pub fn deparse_where_clause(groups: &[Vec<Qual>]) -> String {
+    groups
+        .iter()
+        .filter(|group| !group.is_empty())
+        .map(|group| {
+            let parts: Vec<String> = group.iter().map(Qual::deparse).collect();
+            if parts.len() > 1 {
+                format!("({})", parts.join(" or "))
+            } else {
+                parts.into_iter().next().unwrap()
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" and ")
+}
+
+#[cfg(test)]
+mod qual_tests {
+    use super::*;
+
+    fn eq_qual(field: &str, value: i32) -> Qual {
+        Qual {
+            field: field.to_string(),
+            operator: "=".to_string(),
+            value: Value::Cell(Cell::I32(value)),
+            use_or: false,
+        }
+    }
+
+    fn in_qual(field: &str, values: Vec<i32>) -> Qual {
+        Qual {
+            field: field.to_string(),
+            operator: "=".to_string(),
+            value: Value::Array(values.into_iter().map(Cell::I32).collect()),
+            use_or: true,
+        }
+    }
+
+    #[test]
+    fn deparse_renders_simple_equality() {
+        assert_eq!(eq_qual("id", 1).deparse(), "id = 1");
+    }
+
+    #[test]
+    fn deparse_rewrites_array_as_in_list() {
+        assert_eq!(in_qual("id", vec![1, 2, 3]).deparse(), "id in (1, 2, 3)");
+    }
+
+    #[test]
+    fn deparse_where_clause_ands_single_member_groups() {
+        let groups = vec![vec![eq_qual("id", 1)], vec![eq_qual("status", 2)]];
+        assert_eq!(deparse_where_clause(&groups), "id = 1 and status = 2");
+    }
+
+    #[test]
+    fn deparse_where_clause_ors_within_a_group() {
+        let groups = vec![vec![eq_qual("a", 1), eq_qual("b", 2)]];
+        assert_eq!(deparse_where_clause(&groups), "(a = 1 or b = 2)");
+    }
+
+    #[test]
+    fn deparse_where_clause_does_not_merge_independent_in_lists() {
+        // `id IN (1,2) AND status IN (3,4)` must stay AND'd, not collapse
+        // into one OR group just because each qual's own use_or is true.
+        let groups = vec![vec![in_qual("id", vec![1, 2])], vec![in_qual("status", vec![3, 4])]];
+        assert_eq!(
+            deparse_where_clause(&groups),
+            "id in (1, 2) and status in (3, 4)"
+        );
+    }
+
+    #[test]
+    fn deparse_quotes_timestamptz_literal() {
+        let value = TimestampWithTimeZone::try_from("2024-01-01 00:00:00+00").unwrap();
+        let qual = Qual {
+            field: "created_at".to_string(),
+            operator: "=".to_string(),
+            value: Value::Cell(Cell::TimestampTz(value)),
+            use_or: false,
+        };
+        let deparsed = qual.deparse();
+        assert!(
+            deparsed.starts_with("created_at = '") && deparsed.ends_with('\''),
+            "timestamptz literal must be quoted, got: {}",
+            deparsed
+        );
+    }
+}
+
+/// Type of join to be pushed down to a foreign join scan
+///
+/// Mirrors the `JoinType` Postgres passes to `GetForeignJoinPaths`, restricted
+/// to the kinds a two-relation remote join can satisfy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinType {
+    Inner,
+    Left,
+    Right,
+    Full,
+}
+
+/// Which side of a [`Join`] a [`JoinColumn`] refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinSide {
+    Left,
+    Right,
+}
+
+/// A column reference tagged with the [`Join`] relation it belongs to
+///
+/// Both joined tables may have a same-named column (e.g. `id`), so a plain
+/// column name isn't enough to tell which side a requested column, or a
+/// column in a combined [`Row`], actually comes from.
+#[derive(Debug, Clone)]
+pub struct JoinColumn {
+    pub side: JoinSide,
+    pub name: String,
+}
+
+impl JoinColumn {
+    /// Qualify this column with its side, e.g. `left.id` vs. `right.id`
+    ///
+    /// Use this as the [`Row`] column name when pushing a cell from
+    /// [`begin_join_scan`](ForeignDataWrapper::begin_join_scan)'s combined
+    /// results, so same-named columns from either side don't collide.
+    pub fn qualified_name(&self) -> String {
+        match self.side {
+            JoinSide::Left => format!("left.{}", self.name),
+            JoinSide::Right => format!("right.{}", self.name),
+        }
+    }
+}
+
+/// A join condition between a column of the left relation and a column of
+/// the right relation, e.g. `orders.customer_id = customers.id`
+///
+/// Unlike [`Qual`], whose `value` is always a literal or list of literals,
+/// a join condition compares two columns rather than a column against a
+/// literal, so it needs an explicit field reference on both sides instead
+/// of reusing the WHERE-clause `Qual` type.
+#[derive(Debug, Clone)]
+pub struct JoinQual {
+    /// column name on the left (outer) relation
+    pub left_field: String,
+
+    /// column name on the right (inner) relation
+    pub right_field: String,
+
+    pub operator: String,
+}
+
+/// A candidate join between two foreign tables on the same server
+///
+/// Only offered to [`begin_join_scan`](ForeignDataWrapper::begin_join_scan)
+/// when the outer and inner relations share the same server, so `left_options`
+/// and `right_options` are the two tables' `CREATE FOREIGN TABLE` options.
+#[derive(Debug, Clone)]
+pub struct Join {
+    /// kind of join, e.g. inner, left, right or full
+    pub kind: JoinType,
+
+    /// options of the outer (left) foreign table
+    pub left_options: HashMap<String, String>,
+
+    /// options of the inner (right) foreign table
+    pub right_options: HashMap<String, String>,
+
+    /// join conditions between columns of the two relations
+    pub quals: Vec<JoinQual>,
+}
+
 /// Query sort, a.k.a `ORDER BY` clause
 ///
 /// ## Examples
@@ -337,8 +575,33 @@ pub trait ForeignDataWrapper {
     /// You can do any initalization in this function, like saving connection
     /// info or API url in an variable, but don't do heavy works like database
     /// connection or API call.
+    ///
+    /// Note the scan and modify paths build `options` by merging the server's
+    /// options with the current role's `CREATE USER MAPPING` options, so keys
+    /// from both arrive here in one map; they do not call
+    /// [`user_mapping`](Self::user_mapping).
     fn new(options: &HashMap<String, String>) -> Self;
 
+    /// Receive a role's `CREATE USER MAPPING` options
+    ///
+    /// For example,
+    ///
+    /// ```sql
+    /// create user mapping for current_user
+    ///   server my_helloworld_server
+    ///   options (
+    ///     username 'foo',
+    ///     password 'bar'
+    /// );
+    /// ```
+    ///
+    /// `options` passed here will be a hashmap { 'username' -> 'foo', 'password' -> 'bar' }.
+    ///
+    /// The default implementation does nothing. The crate's scan and modify
+    /// entry points do not call this method; they pass the user mapping
+    /// options to [`new`](Self::new), merged with the server's options.
+    fn user_mapping(&mut self, _options: &HashMap<String, String>) {}
+
     /// Obtain relation size estimates for a foreign table
     ///
     /// Return the expected number of rows and row size (in bytes) by the
@@ -384,11 +647,63 @@ pub trait ForeignDataWrapper {
     /// [See more details](https://www.postgresql.org/docs/current/fdw-callbacks.html#FDW-CALLBACKS-SCAN).
     fn re_scan(&mut self) {}
 
+    /// Called when planning a join between two foreign tables on the same server
+    ///
+    /// - `join` - the candidate join, including its kind, the two relations'
+    ///   options and the join conditions between them
+    /// - `quals` - `WHERE` clause pushed down, applied after the join
+    /// - `columns` - target columns to be queried, each tagged with which
+    ///   relation it belongs to (both sides may have same-named columns)
+    /// - `sorts` - `ORDER BY` clause pushed down
+    /// - `limit` - `LIMIT` clause pushed down
+    ///
+    /// Return `Some(())` to accept the join and produce combined [`Row`]s
+    /// covering columns from both relations through [`iter_scan`](Self::iter_scan).
+    /// Push cells onto the combined `Row` using [`JoinColumn::qualified_name`]
+    /// rather than the bare column name, so a same-named column on the other
+    /// side doesn't collide with it. The default implementation returns
+    /// `None`, in which case Postgres falls back to scanning both relations
+    /// and joining them locally.
+    fn begin_join_scan(
+        &mut self,
+        _join: &Join,
+        _quals: &[Qual],
+        _columns: &[JoinColumn],
+        _sorts: &[Sort],
+        _limit: &Option<Limit>,
+    ) -> Option<()> {
+        None
+    }
+
     /// Called when end the scan
     ///
     /// [See more details](https://www.postgresql.org/docs/current/fdw-callbacks.html#FDW-CALLBACKS-SCAN).
     fn end_scan(&mut self);
 
+    /// Return the name of the `rowid_column` option, if the foreign table has one
+    ///
+    /// The default returns `None`. The scan path does not call this method,
+    /// so no rowid column is added to the scan tuple through it.
+    fn get_row_id_column(&self) -> Option<String> {
+        None
+    }
+
+    /// Called to re-fetch a row for an `EvalPlanQual` recheck
+    ///
+    /// - `rowid` - the `rowid_column` cell identifying the row
+    ///
+    /// This is the only row-locking callback Postgres actually calls back
+    /// into the FDW for ([`RefetchForeignRow`](https://www.postgresql.org/docs/current/fdw-row-locking.html));
+    /// there is no separate "lock/fetch a row" hook, `GetForeignRowMarkType`
+    /// only selects which row-mark strategy to use. As the row may have
+    /// changed or disappeared since it was locked, this must issue an
+    /// independent remote fetch keyed off `rowid` alone and must not assume
+    /// a one-to-one pairing with any earlier call, so nothing that depends
+    /// on call ordering should be cached.
+    fn re_fetch_row(&mut self, _rowid: &Cell) -> Option<Row> {
+        None
+    }
+
     /// Called when begin executing a foreign table modification operation.
     ///
     /// - `options` - the options defined when `CREATE FOREIGN TABLE`
@@ -435,6 +750,29 @@ pub trait ForeignDataWrapper {
     /// [See more details](https://www.postgresql.org/docs/current/fdw-callbacks.html#FDW-CALLBACKS-UPDATE).
     fn delete(&mut self, _rowid: &Cell) {}
 
+    /// Return the preferred number of rows to batch together for a bulk insert
+    ///
+    /// Defaults to `1`, i.e. no batching, so [`insert`](Self::insert) is
+    /// called once per row. Return a larger size to have rows accumulated
+    /// and passed to [`insert_batch`](Self::insert_batch) instead.
+    fn modify_batch_size(&self) -> usize {
+        1
+    }
+
+    /// Called to insert a batch of rows into the foreign table at once
+    ///
+    /// - rows - the new rows to be inserted
+    ///
+    /// Lets API- and SQL-backed wrappers coalesce many rows into a single
+    /// remote request, e.g. a multi-row `INSERT` or a batched HTTP POST, for
+    /// bulk `INSERT ... SELECT` or `COPY`. The default implementation loops
+    /// over [`insert`](Self::insert).
+    fn insert_batch(&mut self, rows: &[Row]) {
+        for row in rows {
+            self.insert(row);
+        }
+    }
+
     /// Called when end the table update
     ///
     /// [See more details](https://www.postgresql.org/docs/current/fdw-callbacks.html#FDW-CALLBACKS-UPDATE).
@@ -457,6 +795,9 @@ pub trait ForeignDataWrapper {
         fdw_routine.GetForeignPlan = Some(scan::get_foreign_plan::<Self>);
         fdw_routine.ExplainForeignScan = Some(scan::explain_foreign_scan::<Self>);
 
+        // join pushdown, only offered when both relations share the same server
+        fdw_routine.GetForeignJoinPaths = Some(scan::get_foreign_join_paths::<Self>);
+
         // scan phase
         fdw_routine.BeginForeignScan = Some(scan::begin_foreign_scan::<Self>);
         fdw_routine.IterateForeignScan = Some(scan::iterate_foreign_scan::<Self>);
@@ -468,10 +809,16 @@ pub trait ForeignDataWrapper {
         fdw_routine.PlanForeignModify = Some(modify::plan_foreign_modify::<Self>);
         fdw_routine.BeginForeignModify = Some(modify::begin_foreign_modify::<Self>);
         fdw_routine.ExecForeignInsert = Some(modify::exec_foreign_insert::<Self>);
+        fdw_routine.GetForeignModifyBatchSize = Some(modify::get_foreign_modify_batch_size::<Self>);
+        fdw_routine.ExecForeignBatchInsert = Some(modify::exec_foreign_batch_insert::<Self>);
         fdw_routine.ExecForeignDelete = Some(modify::exec_foreign_delete::<Self>);
         fdw_routine.ExecForeignUpdate = Some(modify::exec_foreign_update::<Self>);
         fdw_routine.EndForeignModify = Some(modify::end_foreign_modify::<Self>);
 
+        // row locking / EvalPlanQual rechecks
+        fdw_routine.GetForeignRowMarkType = Some(modify::get_foreign_row_mark_type::<Self>);
+        fdw_routine.RefetchForeignRow = Some(modify::refetch_foreign_row::<Self>);
+
         Self::fdw_routine_hook(&mut fdw_routine);
         fdw_routine.into_pg_boxed()
     }
@@ -499,6 +846,10 @@ pub trait ForeignDataWrapper {
     ///             FOREIGN_SERVER_RELATION_ID => {
     ///                 // check option here when create server
     ///             }
+    ///             FOREIGN_USER_MAPPING_RELATION_ID => {
+    ///                 // check a required credential when create user mapping
+    ///                 check_options_contain(&opt_list, "username");
+    ///             }
     ///             FOREIGN_TABLE_RELATION_ID => {
     ///                 // check option here when create foreign table
     ///             }
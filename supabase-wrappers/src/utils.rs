@@ -0,0 +1,107 @@
+//! Reusable helpers shared across foreign data wrapper implementations
+//!
+
+use pgx::pg_sys::Oid;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// A cache of live connections (or HTTP clients), keyed by foreign server
+///
+/// The FDW trait's [`new`](crate::interface::ForeignDataWrapper::new) docs
+/// explicitly tell authors not to connect there, which otherwise pushes
+/// connection setup into `begin_scan`/`begin_modify`, re-establishing it on
+/// every scan. `ConnectionCache` memoizes one connection per server for the
+/// lifetime of the backend instead, mirroring how postgres_fdw's
+/// `connection.c` caches one libpq connection per foreign-server and user
+/// mapping pair and reuses it across statements.
+///
+/// `K` identifies a server, e.g. its `Oid`, and `C` is the connection or
+/// client type being cached.
+pub struct ConnectionCache<K, C> {
+    conns: HashMap<K, C>,
+}
+
+impl<K, C> Default for ConnectionCache<K, C> {
+    fn default() -> Self {
+        Self {
+            conns: HashMap::new(),
+        }
+    }
+}
+
+impl<K, C> ConnectionCache<K, C>
+where
+    K: Eq + std::hash::Hash,
+{
+    /// Create an empty connection cache
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return the cached connection for `key`, creating it with `connect` if absent
+    ///
+    /// `validate` is called on an existing entry before it is reused; if it
+    /// returns `false` the entry is dropped and `connect` is invoked again,
+    /// e.g. to recover from a connection that died since it was cached.
+    ///
+    /// `connect` returns `Result` rather than `C` directly, since a real
+    /// connect can fail (bad credentials, host down); that error is handed
+    /// back to the caller to raise as a normal Postgres `ERROR` instead of
+    /// forcing a panic to represent it.
+    pub fn get_or_connect<F, V, E>(&mut self, key: K, mut validate: V, connect: F) -> Result<&mut C, E>
+    where
+        F: FnOnce() -> Result<C, E>,
+        V: FnMut(&mut C) -> bool,
+    {
+        let stale = match self.conns.get_mut(&key) {
+            Some(conn) => !validate(conn),
+            None => false,
+        };
+        if stale {
+            self.conns.remove(&key);
+        }
+        match self.conns.entry(key) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => {
+                let conn = connect()?;
+                Ok(entry.insert(conn))
+            }
+        }
+    }
+
+    /// Remove and drop the cached connection for `key`
+    pub fn invalidate(&mut self, key: &K) {
+        self.conns.remove(key);
+    }
+
+    /// Drop all cached connections
+    pub fn clear(&mut self) {
+        self.conns.clear();
+    }
+}
+
+impl<K, C> ConnectionCache<K, C>
+where
+    K: Eq + std::hash::Hash + 'static,
+    C: 'static,
+{
+    /// Register `cache` to be cleared when the current transaction aborts
+    ///
+    /// Mirrors postgres_fdw's `connection.c`, which registers a
+    /// `RegisterXactCallback` handler to discard its connections on abort
+    /// rather than reusing one left over from a rolled-back transaction.
+    /// Wrappers keep their cache behind a `static` (e.g. a
+    /// `once_cell::sync::Lazy<Mutex<ConnectionCache<..>>>`). The callback only
+    /// covers the transaction it is registered in, so pass the cache here in
+    /// each transaction that uses it, e.g. from
+    /// [`new`](crate::interface::ForeignDataWrapper::new).
+    pub fn register_abort_teardown(cache: &'static Mutex<Self>) {
+        pgx::register_xact_callback(pgx::PgXactCallbackEvent::Abort, move || {
+            cache.lock().unwrap().clear();
+        });
+    }
+}
+
+/// A cache key identifying a foreign server by its catalog `Oid`
+pub type ServerId = Oid;